@@ -1,53 +1,488 @@
-use std::{cell::RefCell, time::Duration};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
 
 use crate::{create_icp_signer, get_rpc_service_base, get_rpc_service_sepolia};
 
 use alloy::{
+    eips::BlockId,
     network::EthereumWallet,
-    eips::BlockNumberOrTag,
-    primitives::{address, Address, U256},
+    primitives::{address, Address, TxHash, U256},
     providers::{Provider, ProviderBuilder},
     rpc::types::{Filter, Log},
     signers::Signer,
     sol,
     sol_types::SolEvent,
-    transports::icp::IcpConfig,
+    transports::icp::{IcpConfig, RpcServices},
 };
 
+use candid::CandidType;
 use ic_cdk_timers::TimerId;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of blocks requested in a single `eth_getLogs` call, to
+/// keep RPC responses within the canister's response size limits when
+/// catching up a large gap.
+const BLOCK_WINDOW: u64 = 500;
+
+/// How often the scheduler wakes up to check which tasks are due. Each
+/// individual [`Task`] still runs on its own, coarser period; this is just
+/// the resolution at which due tasks are noticed.
+const SCHEDULER_TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of times to poll for a transaction receipt before treating a mint
+/// as timed out rather than confirmed.
+const MINT_CONFIRMATION_ATTEMPTS: u8 = 5;
+
+/// Number of attempts (including the first) a mint job gets before it is
+/// dropped from the queue and recorded as a failure.
+const MINT_MAX_ATTEMPTS: u8 = 5;
+
+/// Base of the exponential backoff applied between mint retries, in blocks.
+const MINT_BACKOFF_BASE_BLOCKS: u64 = 5;
+
+/// Default cooldown before a whale that was already minted to becomes
+/// eligible again, roughly one week of Base blocks at a 2s block time.
+/// Overridable via `set_whale_cooldown_blocks`.
+const DEFAULT_WHALE_COOLDOWN_BLOCKS: u64 = 302_400;
+
+/// Number of blocks to wait before retrying a mint job that has failed
+/// `attempts` times (`attempts` includes the failure just recorded), doubling
+/// the wait on every attempt.
+fn mint_backoff_blocks(attempts: u8) -> u64 {
+    MINT_BACKOFF_BASE_BLOCKS * 2u64.pow(attempts.saturating_sub(1) as u32)
+}
+
+/// Whether a whale last seen at `last_seen_block` is still within
+/// `cooldown_blocks` of `latest_block`, and so should not be minted to again.
+fn is_whale_on_cooldown(latest_block: u64, last_seen_block: u64, cooldown_blocks: u64) -> bool {
+    latest_block.saturating_sub(last_seen_block) < cooldown_blocks
+}
+
+/// The last block of the `BLOCK_WINDOW`-sized chunk starting at `from`,
+/// capped at `latest_block` so the final chunk of a range isn't overshot.
+fn block_window_end(from: u64, latest_block: u64) -> u64 {
+    (from + BLOCK_WINDOW - 1).min(latest_block)
+}
+
+/// A registered watch: which token transfers to look for on which chain, and
+/// which NFT contract (on which chain) to mint from when a transfer clears
+/// `min_value`. Replaces the single hard-coded token/chain/threshold/NFT
+/// contract, so the canister can watch several ERC-20 tokens across several
+/// chains at once.
+///
+/// The chain to poll and the chain to mint on are each described by a plain
+/// `RpcServices` value plus (for the mint side) its numeric chain id, rather
+/// than a closed enum of the chains this canister happened to ship with.
+/// Watching or minting on a chain nobody anticipated is then just a matter of
+/// calling `register_watch` with a different `RpcServices`/chain id, not a
+/// code change.
+#[derive(Clone, CandidType, Serialize, Deserialize)]
+struct WatchConfig {
+    watch_rpc_service: RpcServices,
+    token_address: Address,
+    // Must be `Erc20::Transfer::SIGNATURE`; `register_watch` rejects
+    // anything else, since that's the only log shape the decode step
+    // understands.
+    event_signature: String,
+    min_value: U256,
+    nft_contract: Address,
+    mint_rpc_service: RpcServices,
+    mint_chain_id: u64,
+    // The highest block of `watch_rpc_service`'s chain whose logs have been
+    // fully processed for this watch. `None` until it has polled at least once.
+    last_processed_block: Option<u64>,
+}
 
-const POLL_LIMIT: usize = 3;
+/// A mint that still needs to be sent (or retried) for `target`, on behalf of
+/// the watch that queued it. Pushed onto the queue by the transfer monitor
+/// and drained by `Task::MintRetry`, so a dropped future or an RPC hiccup can
+/// no longer silently lose a mint.
+#[derive(Clone, CandidType, Serialize, Deserialize)]
+struct MintJob {
+    target: Address,
+    nft_contract: Address,
+    mint_rpc_service: RpcServices,
+    mint_chain_id: u64,
+    attempts: u8,
+    // The mint is not retried before this block (of `mint_chain_id`),
+    // implementing the exponential backoff between attempts.
+    next_eligible_block: u64,
+}
+
+/// An independent unit of periodic canister work, each with its own cadence.
+/// The scheduler drives all of them from a single underlying timer instead
+/// of every feature registering its own `ic_cdk_timers` timer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, CandidType, Serialize, Deserialize)]
+enum Task {
+    /// Polls every registered watch for transfer logs.
+    UsdcTransferMonitor,
+    /// Refreshes the nonce cache from the chain's pending transaction count.
+    NonceResync,
+    /// Sends queued mints, retrying those that previously failed or timed out.
+    MintRetry,
+}
+
+impl Task {
+    const ALL: [Task; 3] = [
+        Task::UsdcTransferMonitor,
+        Task::NonceResync,
+        Task::MintRetry,
+    ];
+
+    /// The minimum time that must elapse between two runs of this task.
+    fn period(self) -> Duration {
+        match self {
+            Task::UsdcTransferMonitor => Duration::from_secs(10),
+            Task::NonceResync => Duration::from_secs(60),
+            Task::MintRetry => Duration::from_secs(15),
+        }
+    }
+}
+
+/// Number of consecutive send failures for a signer address after which its
+/// cached nonce is no longer trusted and must be re-read from the chain.
+const NONCE_FAILURE_THRESHOLD: u8 = 1;
+
+/// Caches, per signer address *and chain id* (the same address has an
+/// independent nonce on every chain), the next nonce to use when sending a
+/// transaction. This avoids a `get_transaction_count` round trip on every
+/// mint while still recovering from dropped or failed transactions: the
+/// cached nonce is only advanced after a send succeeds, and is invalidated
+/// on failure so the next call re-reads the pending transaction count.
+#[derive(Default)]
+struct NonceCache {
+    next_nonce: HashMap<(Address, u64), u64>,
+    last_refreshed_at: HashMap<(Address, u64), u64>,
+    consecutive_failures: HashMap<(Address, u64), u8>,
+}
+
+impl NonceCache {
+    /// Returns the cached nonce for `address` on `chain_id`, unless it has
+    /// been invalidated by too many consecutive send failures.
+    fn cached(&self, address: Address, chain_id: u64) -> Option<u64> {
+        if self
+            .consecutive_failures
+            .get(&(address, chain_id))
+            .copied()
+            .unwrap_or(0)
+            >= NONCE_FAILURE_THRESHOLD
+        {
+            return None;
+        }
+        self.next_nonce.get(&(address, chain_id)).copied()
+    }
+
+    /// Records a nonce freshly read from the chain and clears any failure
+    /// streak for `address` on `chain_id`.
+    fn refresh(&mut self, address: Address, chain_id: u64, nonce: u64) {
+        self.next_nonce.insert((address, chain_id), nonce);
+        self.consecutive_failures.remove(&(address, chain_id));
+        self.last_refreshed_at
+            .insert((address, chain_id), ic_cdk::api::time());
+    }
+
+    /// Advances the cache past `used_nonce` after a transaction using it was
+    /// successfully sent.
+    fn advance(&mut self, address: Address, chain_id: u64, used_nonce: u64) {
+        self.next_nonce.insert((address, chain_id), used_nonce + 1);
+        self.consecutive_failures.remove(&(address, chain_id));
+    }
+
+    /// Marks the cached entry for `address` on `chain_id` as stale after a
+    /// failed send, so the next mint re-reads the nonce from the chain
+    /// instead of reusing it.
+    fn invalidate(&mut self, address: Address, chain_id: u64) {
+        *self
+            .consecutive_failures
+            .entry((address, chain_id))
+            .or_insert(0) += 1;
+    }
+}
+
+/// The next nonce cached for a signer address on a given chain, and when it
+/// was last refreshed from the chain, as returned by [`nonce_status`]. The
+/// cached nonce is reported even after a failed send has invalidated it.
+#[derive(CandidType, Serialize, Deserialize)]
+struct NonceStatus {
+    cached_nonce: Option<u64>,
+    last_refreshed_at: Option<u64>,
+}
 
 thread_local! {
-    static NONCE: RefCell<Option<u64>> = const { RefCell::new(None) };
+    static NONCE_CACHE: RefCell<NonceCache> = RefCell::new(NonceCache::default());
 }
 
 struct State {
-    timer_id: Option<TimerId>,
     logs: Vec<String>,
     poll_count: usize,
+    // The id of the single `ic_cdk_timers` timer backing the scheduler. All
+    // `Task`s are driven from this one timer rather than each owning their own.
+    scheduler_timer_id: Option<TimerId>,
+    // When each task last ran, in nanoseconds since epoch (`ic_cdk::api::time()`).
+    task_last_run: HashMap<Task, u64>,
+    // Tasks default to enabled; only disabled tasks are recorded here.
+    task_disabled: HashMap<Task, bool>,
+    // Whether a spawned instance of a task is still in flight. A tick skips
+    // a task here instead of spawning a second concurrent instance on top.
+    task_running: HashMap<Task, bool>,
+    // Registered watches, keyed by an id handed out on registration. Each
+    // owns its own sync cursor, persisted across upgrades so a restart never
+    // re-scans or skips a range.
+    watches: HashMap<u64, WatchConfig>,
+    next_watch_id: u64,
+    // The watch registered by `watch_usdc_transfer_start` for backwards
+    // compatibility with the original single hard-coded watch.
+    default_watch_id: Option<u64>,
+    // Mints waiting to be sent or retried. Populated by the transfer monitor,
+    // drained by `Task::MintRetry`.
+    mint_queue: VecDeque<MintJob>,
+    // Mints that were dropped after exhausting `MINT_MAX_ATTEMPTS`.
+    mint_failures: Vec<String>,
+    // Addresses a mint has been queued for, and the latest block of the
+    // watched chain at the poll that queued it. An address here is skipped
+    // until `whale_cooldown_blocks` has elapsed, so a whale making several large transfers is only minted
+    // to once per cooldown window instead of once per transfer.
+    whale_waitlist: HashMap<Address, u64>,
+    whale_cooldown_blocks: u64,
 }
 
 impl State {
     fn default() -> State {
         State {
-            // Store the id of the IC_CDK timer used for polling the EVM RPC periodically.
-            // This id can be used to cancel the timer before the configured `POLL_LIMIT`
-            // has been reached.
-            timer_id: None,
             // The logs returned by the EVM are stored here for display in the frontend.
             logs: Vec::new(),
-            // The number of polls made. Polls finish automatically, once the `POLL_LIMIT`
-            // has been reached. This count is used to create a good interactive UI experience.
+            // The number of polls made since the watch was last (re)started.
             poll_count: 0,
+            scheduler_timer_id: None,
+            task_last_run: HashMap::new(),
+            task_disabled: HashMap::new(),
+            task_running: HashMap::new(),
+            watches: HashMap::new(),
+            next_watch_id: 0,
+            default_watch_id: None,
+            mint_queue: VecDeque::new(),
+            mint_failures: Vec::new(),
+            whale_waitlist: HashMap::new(),
+            whale_cooldown_blocks: DEFAULT_WHALE_COOLDOWN_BLOCKS,
         }
     }
 }
 
+/// The subset of `State` that needs to survive a canister upgrade. Timer ids
+/// and in-memory task bookkeeping are intentionally excluded: timers do not
+/// survive an upgrade regardless, so callers are expected to re-enable the
+/// watch afterwards, but the registered watches and their sync cursors must
+/// not reset or a restart would either replay or skip a range of blocks.
+#[derive(Default, CandidType, Serialize, Deserialize)]
+struct StableState {
+    watches: HashMap<u64, WatchConfig>,
+    next_watch_id: u64,
+    default_watch_id: Option<u64>,
+}
+
+#[ic_cdk::pre_upgrade]
+fn pre_upgrade() {
+    let stable_state = STATE.with_borrow(|state| StableState {
+        watches: state.watches.clone(),
+        next_watch_id: state.next_watch_id,
+        default_watch_id: state.default_watch_id,
+    });
+    ic_cdk::storage::stable_save((stable_state,)).expect("failed to save stable state");
+}
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    let (stable_state,): (StableState,) = ic_cdk::storage::stable_restore().unwrap_or_default();
+    STATE.with_borrow_mut(|state| {
+        state.watches = stable_state.watches;
+        state.next_watch_id = stable_state.next_watch_id;
+        state.default_watch_id = stable_state.default_watch_id;
+    });
+}
+
 thread_local! {
     static STATE: RefCell<State> = RefCell::new(State::default());
 }
 
+/// Starts the scheduler's single timer if it isn't already running. Safe to
+/// call repeatedly; a second call while the scheduler is already running is
+/// a no-op.
+fn ensure_scheduler_running() {
+    STATE.with_borrow_mut(|state| {
+        if state.scheduler_timer_id.is_some() {
+            return;
+        }
+        let timer_id = ic_cdk_timers::set_timer_interval(SCHEDULER_TICK_INTERVAL, run_due_tasks);
+        state.scheduler_timer_id = Some(timer_id);
+    });
+}
+
+/// Runs on every scheduler tick: finds every enabled, not-already-running
+/// task whose period has elapsed since its last run, marks it as run now,
+/// then spawns it. Tasks run concurrently with each other and never block
+/// the tick itself.
+fn run_due_tasks() {
+    let now = ic_cdk::api::time();
+    let due: Vec<Task> = STATE.with_borrow_mut(|state| {
+        Task::ALL
+            .into_iter()
+            .filter(|task| {
+                if state.task_disabled.get(task).copied().unwrap_or(false) {
+                    return false;
+                }
+                // A task that overran its own period must finish before it
+                // is run again, or two concurrent instances could race on
+                // the same state (e.g. two `poll_watch` runs reading the
+                // same cursor, or two mints reading the same cached nonce).
+                if state.task_running.get(task).copied().unwrap_or(false) {
+                    return false;
+                }
+                let last_run = state.task_last_run.get(task).copied().unwrap_or(0);
+                Duration::from_nanos(now.saturating_sub(last_run)) >= task.period()
+            })
+            .inspect(|task| {
+                state.task_last_run.insert(*task, now);
+                state.task_running.insert(*task, true);
+            })
+            .collect()
+    });
+
+    for task in due {
+        ic_cdk::futures::spawn(run_task(task));
+    }
+}
+
+/// Executes a single due task, clearing its in-flight flag when done
+/// (including on failure) so the next scheduler tick is free to run it again.
+async fn run_task(task: Task) {
+    match task {
+        Task::UsdcTransferMonitor => poll_all_watches().await,
+        Task::NonceResync => resync_nonce().await,
+        Task::MintRetry => process_mint_queue().await,
+    }
+    STATE.with_borrow_mut(|state| state.task_running.insert(task, false));
+}
+
+/// Sends every mint job whose backoff has elapsed, waits for it to confirm,
+/// and either drops it, retries it with exponential backoff, or records it
+/// as a permanent failure. Jobs can target different mint chains, so the
+/// current block is fetched once per distinct chain rather than once overall.
+async fn process_mint_queue() {
+    let jobs: Vec<MintJob> = STATE.with_borrow_mut(|state| state.mint_queue.drain(..).collect());
+    if jobs.is_empty() {
+        return;
+    }
+
+    // Jobs can target different mint chains; fetch the current block once
+    // per distinct chain id rather than once per job.
+    let mut seen_chains: HashMap<u64, RpcServices> = HashMap::new();
+    for job in &jobs {
+        seen_chains
+            .entry(job.mint_chain_id)
+            .or_insert_with(|| job.mint_rpc_service.clone());
+    }
+
+    let mut current_blocks: HashMap<u64, u64> = HashMap::new();
+    for (chain_id, rpc_service) in seen_chains {
+        let config = IcpConfig::new(rpc_service);
+        let provider = ProviderBuilder::new().on_icp(config);
+        if let Ok(block) = provider.get_block_number().await {
+            current_blocks.insert(chain_id, block);
+        }
+    }
+
+    let mut due = Vec::new();
+    for job in jobs {
+        match current_blocks.get(&job.mint_chain_id) {
+            Some(&current_block) if job.next_eligible_block <= current_block => {
+                due.push((job, current_block));
+            }
+            _ => STATE.with_borrow_mut(|state| state.mint_queue.push_back(job)),
+        }
+    }
+
+    for (job, current_block) in due {
+        match mint_new_whale_nft(
+            job.target,
+            job.nft_contract,
+            job.mint_rpc_service.clone(),
+            job.mint_chain_id,
+        )
+        .await
+        {
+            Ok(tx_hash) => {
+                // The waitlist entry was already written when the mint was
+                // enqueued (so duplicate transfers can't queue a second mint
+                // while this one is still pending); nothing left to record
+                // here beyond the log line.
+                STATE.with_borrow_mut(|state| {
+                    state.logs.push(format!(
+                        "Minted whale NFT for {:?} in {tx_hash:?}",
+                        job.target
+                    ));
+                });
+            }
+            Err(err) => {
+                let attempts = job.attempts + 1;
+                if attempts >= MINT_MAX_ATTEMPTS {
+                    STATE.with_borrow_mut(|state| {
+                        state.mint_failures.push(format!(
+                            "giving up minting for {:?} after {attempts} attempts: {err}",
+                            job.target
+                        ));
+                    });
+                } else {
+                    let backoff = mint_backoff_blocks(attempts);
+                    STATE.with_borrow_mut(|state| {
+                        state.mint_queue.push_back(MintJob {
+                            target: job.target,
+                            nft_contract: job.nft_contract,
+                            mint_rpc_service: job.mint_rpc_service,
+                            mint_chain_id: job.mint_chain_id,
+                            attempts,
+                            next_eligible_block: current_block + backoff,
+                        });
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Re-reads the mint signer's pending transaction count from the chain and
+/// refreshes the nonce cache with it, so a cache that fell out of sync with
+/// the chain (e.g. after a dropped transaction) heals on its own. The same
+/// signer address has an independent nonce sequence on every mint chain
+/// currently in use, so each one is resynced separately.
+async fn resync_nonce() {
+    let signer = create_icp_signer().await;
+    let address = signer.address();
+
+    let mut mint_chains: HashMap<u64, RpcServices> = HashMap::new();
+    STATE.with_borrow(|state| {
+        for watch in state.watches.values() {
+            mint_chains
+                .entry(watch.mint_chain_id)
+                .or_insert_with(|| watch.mint_rpc_service.clone());
+        }
+    });
+
+    for (chain_id, rpc_service) in mint_chains {
+        let config = IcpConfig::new(rpc_service);
+        let provider = ProviderBuilder::new().on_icp(config);
+
+        if let Ok(nonce) = provider
+            .get_transaction_count(address)
+            .block_id(BlockId::pending())
+            .await
+        {
+            NONCE_CACHE.with_borrow_mut(|cache| cache.refresh(address, chain_id, nonce));
+        }
+    }
+}
 
 // Codegen from ABI file to interact with the contract.
 sol!(
@@ -57,171 +492,323 @@ sol!(
     "src/abi/WhaleNFT.json"
 );
 
+// Every compliant ERC-20 emits the same `Transfer(address,address,uint256)`
+// event regardless of which token it is, so a single binding decodes the
+// transfer logs for every watch rather than one tied to a specific token.
 sol!(
     #[allow(missing_docs, clippy::too_many_arguments)]
     #[sol(rpc)]
-    USDC,
+    Erc20,
     "src/abi/USDC.json"
 );
 
-async fn mint_new_whale_nft(target_address: Address) -> Result<String, String> {
-
+async fn mint_new_whale_nft(
+    target_address: Address,
+    nft_contract: Address,
+    rpc_service: RpcServices,
+    chain_id: u64,
+) -> Result<TxHash, String> {
     // Setup signer
     let signer = create_icp_signer().await;
     let address = signer.address();
 
     // Setup provider
     let wallet = EthereumWallet::from(signer);
-    let rpc_service = get_rpc_service_sepolia();
     let config = IcpConfig::new(rpc_service);
     let provider = ProviderBuilder::new()
         .with_gas_estimation()
         .wallet(wallet)
         .on_icp(config);
 
-    // Attempt to get nonce from thread-local storage
-    let maybe_nonce = NONCE.with_borrow(|maybe_nonce| {
-        // If a nonce exists, the next nonce to use is latest nonce + 1
-        maybe_nonce.map(|nonce| nonce + 1)
-    });
+    // Consult the nonce cache first; only the provider is queried when the
+    // cache has no entry for this address/chain or was invalidated by a
+    // previous failed send.
+    let cached_nonce = NONCE_CACHE.with_borrow(|cache| cache.cached(address, chain_id));
 
-    // If no nonce exists, get it from the provider
-    let nonce = if let Some(nonce) = maybe_nonce {
+    let nonce = if let Some(nonce) = cached_nonce {
         nonce
     } else {
-        provider.get_transaction_count(address).await.unwrap_or(0)
+        // Query the *pending* block tag so transactions that are queued but
+        // not yet mined are accounted for, instead of silently reusing their
+        // nonce.
+        let nonce = provider
+            .get_transaction_count(address)
+            .block_id(BlockId::pending())
+            .await
+            .map_err(|e| format!("{e:?}"))?;
+        NONCE_CACHE.with_borrow_mut(|cache| cache.refresh(address, chain_id, nonce));
+        nonce
     };
 
     // Mint a new NFT
-    let contract = WhaleNFT::new(
-        address!("63A0bfd6a5cdCF446ae12135E2CD86b908659568"),
-        provider.clone(),
-    );
+    let contract = WhaleNFT::new(nft_contract, provider.clone());
 
     match contract
         .newWhale(target_address)
         .nonce(nonce)
-        .chain_id(11155111)
+        .chain_id(chain_id)
         .from(address)
         .send()
         .await
     {
         Ok(builder) => {
-            let node_hash = *builder.tx_hash();
-            let tx_response = provider.get_transaction_by_hash(node_hash).await.unwrap();
-
-            match tx_response {
-                Some(tx) => {
-                    // The transaction has been mined and included in a block, the nonce
-                    // has been consumed. Save it to thread-local storage. Next transaction
-                    // for this address will use a nonce that is = this nonce + 1
-                    NONCE.with_borrow_mut(|nonce| {
-                        *nonce = Some(tx.nonce);
-                    });
-                    Ok(format!("{:?}", tx))
+            let tx_hash = *builder.tx_hash();
+            let tx_response = match provider.get_transaction_by_hash(tx_hash).await {
+                Ok(tx_response) => tx_response,
+                Err(e) => {
+                    NONCE_CACHE.with_borrow_mut(|cache| cache.invalidate(address, chain_id));
+                    return Err(format!("{e:?}"));
+                }
+            };
+
+            let Some(tx) = tx_response else {
+                NONCE_CACHE.with_borrow_mut(|cache| cache.invalidate(address, chain_id));
+                return Err("Could not get transaction.".to_string());
+            };
+
+            // The transaction was accepted by the node and the nonce is now
+            // in flight. Only now do we advance the cache, so a dropped send
+            // never permanently desyncs it.
+            NONCE_CACHE.with_borrow_mut(|cache| cache.advance(address, chain_id, tx.nonce));
+
+            // Wait for the transaction to be included in a block and check
+            // that it didn't revert, so the caller only ever sees a mint as
+            // successful once it is actually confirmed on-chain.
+            for _ in 0..MINT_CONFIRMATION_ATTEMPTS {
+                match provider.get_transaction_receipt(tx_hash).await {
+                    Ok(Some(receipt)) if receipt.status() => return Ok(tx_hash),
+                    Ok(Some(_)) => return Err("Transaction reverted.".to_string()),
+                    _ => continue,
                 }
-                None => Err("Could not get transaction.".to_string()),
             }
+            Err("Timed out waiting for mint confirmation.".to_string())
+        }
+        Err(e) => {
+            NONCE_CACHE.with_borrow_mut(|cache| cache.invalidate(address, chain_id));
+            Err(format!("{:?}", e))
         }
-        Err(e) => Err(format!("{:?}", e)),
     }
-
 }
 
-/// Using the ICP poller for Alloy allows smart contract canisters
-/// to watch EVM blockchain changes easily. In this example, the canister
-/// watches for USDC transfer logs.
-#[ic_cdk::update]
-async fn watch_usdc_transfer_start() -> Result<String, String> {
-    // Don't start a timer if one is already running
-    STATE.with_borrow(|state| {
-        if state.timer_id.is_some() {
-            return Err("Already watching for logs.".to_string());
-        }
-        Ok(())
-    })?;
+/// Queries the chain for transfer logs matching a single registered watch,
+/// since that watch's own last processed block, and queues a whale NFT mint
+/// for the sender of any transfer above its threshold, unless that sender is
+/// still on cooldown.
+///
+/// The range since the cursor is walked in `BLOCK_WINDOW`-sized chunks to
+/// keep each `eth_getLogs` response within the canister's size limits, and
+/// the cursor only advances once every chunk up to the latest block has been
+/// processed, so a failure partway through does not skip the rest of the range.
+async fn poll_watch(watch_id: u64) -> Result<(), String> {
+    let Some(watch) = STATE.with_borrow(|state| state.watches.get(&watch_id).cloned()) else {
+        return Err(format!("no watch registered with id {watch_id}"));
+    };
 
-    let rpc_service = get_rpc_service_base();
-    let config = IcpConfig::new(rpc_service).set_max_response_size(100_000);
+    let config = IcpConfig::new(watch.watch_rpc_service.clone()).set_max_response_size(100_000);
     let provider = ProviderBuilder::new().on_icp(config);
 
-    // This callback will be called every time new logs are received
-    let callback = |incoming_logs: Vec<Log>| {
-        STATE.with_borrow_mut(|state| async {
-            for log in incoming_logs.iter() {
-                let transfer: Log<USDC::Transfer> = log.log_decode().unwrap();
-                let USDC::Transfer { from, to, value } = transfer.data();
-                
-                if value > &U256::from(1_000_000) {
-                    let from_fmt = format!(
-                        "0x{}...{}",
-                        &from.to_string()[2..5],
-                        &from.to_string()[from.to_string().len() - 3..]
-                    );
-                    let to_fmt = format!(
-                        "0x{}...{}",
-                        &to.to_string()[2..5],
-                        &to.to_string()[to.to_string().len() - 3..]
-                    );
+    let latest_block = provider
+        .get_block_number()
+        .await
+        .map_err(|e| format!("{e:?}"))?;
+
+    // On the very first poll there is no cursor yet; start watching from the
+    // current tip instead of replaying the whole chain history.
+    let start_block = watch.last_processed_block.map_or(latest_block, |b| b + 1);
+
+    if start_block > latest_block {
+        return Ok(());
+    }
+
+    let mut from = start_block;
+    while from <= latest_block {
+        let to = block_window_end(from, latest_block);
+
+        let filter = Filter::new()
+            .address(watch.token_address)
+            // By specifying an `event` or `event_signature` we listen for a specific event of the
+            // contract. In this case the `Transfer(address,address,uint256)` event.
+            .event(watch.event_signature.as_str())
+            .from_block(from)
+            .to_block(to);
+
+        let incoming_logs = provider
+            .get_logs(&filter)
+            .await
+            .map_err(|e| format!("{e:?}"))?;
+
+        for log in incoming_logs.iter() {
+            // `event_signature` is validated at registration time to be the
+            // standard ERC-20 `Transfer(address,address,uint256)` event, but
+            // a log that fails to decode against that shape is skipped
+            // rather than unwrapped, so a single malformed log can't take
+            // down every subsequent poll of this watch.
+            let transfer: Log<Erc20::Transfer> = match log.log_decode() {
+                Ok(transfer) => transfer,
+                Err(err) => {
+                    STATE.with_borrow_mut(|state| {
+                        state
+                            .logs
+                            .push(format!("failed to decode transfer log: {err:?}"))
+                    });
+                    continue;
+                }
+            };
+            let Erc20::Transfer { from, to, value } = transfer.data();
+
+            if value > &watch.min_value {
+                let from_fmt = format!(
+                    "0x{}...{}",
+                    &from.to_string()[2..5],
+                    &from.to_string()[from.to_string().len() - 3..]
+                );
+                let to_fmt = format!(
+                    "0x{}...{}",
+                    &to.to_string()[2..5],
+                    &to.to_string()[to.to_string().len() - 3..]
+                );
+                STATE.with_borrow_mut(|state| {
                     state
                         .logs
                         .push(format!("{from_fmt} -> {to_fmt}, value: {value:?}"));
 
-                    // Issue here as we have an async call data when we want to mint a NFT while pulling event
-                    mint_new_whale_nft(*from).await;
-                }
-            }
+                    // Checked (and recorded) against `latest_block`, i.e. a
+                    // block number on `watch.watch_rpc_service`'s chain -- the
+                    // same chain the waitlist entry below is stamped with --
+                    // so this stays meaningful even though the mint itself
+                    // may later be sent on a different chain.
+                    let on_cooldown =
+                        state
+                            .whale_waitlist
+                            .get(from)
+                            .is_some_and(|&last_seen_block| {
+                                is_whale_on_cooldown(
+                                    latest_block,
+                                    last_seen_block,
+                                    state.whale_cooldown_blocks,
+                                )
+                            });
+                    if on_cooldown {
+                        return;
+                    }
 
-            state.poll_count += 1;
-            if state.poll_count >= POLL_LIMIT {
-                state.timer_id.take();
+                    // Mark the whale as seen (and queue the mint) right now,
+                    // not once the mint is confirmed: confirmation can take
+                    // several scheduler ticks, during which this same watch
+                    // -- or another poll of this same batch of logs -- would
+                    // otherwise queue a duplicate mint for the same address.
+                    state.whale_waitlist.insert(*from, latest_block);
+
+                    // Queue the mint instead of sending it inline: minting
+                    // here would spawn a future that this closure drops
+                    // without awaiting, silently losing it on any hiccup.
+                    state.mint_queue.push_back(MintJob {
+                        target: *from,
+                        nft_contract: watch.nft_contract,
+                        mint_rpc_service: watch.mint_rpc_service.clone(),
+                        mint_chain_id: watch.mint_chain_id,
+                        attempts: 0,
+                        next_eligible_block: 0,
+                    });
+                });
             }
-        })
-    };
+        }
+
+        from = to + 1;
+    }
 
-    // Clear the logs and poll count when starting a new watch
     STATE.with_borrow_mut(|state| {
-        state.logs.clear();
-        state.poll_count = 0;
+        if let Some(watch) = state.watches.get_mut(&watch_id) {
+            watch.last_processed_block = Some(latest_block);
+        }
+        state.poll_count += 1;
     });
+    Ok(())
+}
+
+/// Polls every registered watch in turn. A failure on one watch is recorded
+/// in the logs but never stops the others from being polled on this tick.
+async fn poll_all_watches() {
+    let watch_ids: Vec<u64> = STATE.with_borrow(|state| state.watches.keys().copied().collect());
+    for watch_id in watch_ids {
+        if let Err(err) = poll_watch(watch_id).await {
+            STATE.with_borrow_mut(|state| {
+                state
+                    .logs
+                    .push(format!("poll error for watch {watch_id}: {err}"))
+            });
+        }
+    }
+}
+
+/// Enables the USDC transfer monitor task and makes sure the scheduler is
+/// running. Unlike the old fixed-count poller, the watch now keeps running
+/// on its own cadence until explicitly stopped.
+///
+/// Registers the original hard-coded USDC-on-Base -> WhaleNFT-on-Sepolia
+/// watch as the default one if no default watch is currently registered
+/// (other registered watches do not count), so existing callers keep
+/// working unmodified.
+#[ic_cdk::update]
+async fn watch_usdc_transfer_start() -> Result<String, String> {
+    let already_running = STATE.with_borrow(|state| {
+        !state
+            .task_disabled
+            .get(&Task::UsdcTransferMonitor)
+            .copied()
+            .unwrap_or(false)
+            && state.task_last_run.contains_key(&Task::UsdcTransferMonitor)
+    });
+    if already_running {
+        return Err("Already watching for logs.".to_string());
+    }
 
-    let usdt_token_address = address!("833589fcd6edb6e08f4c7c32d4f71b54bda02913");
-    let filter = Filter::new()
-        .address(usdt_token_address)
-        // By specifying an `event` or `event_signature` we listen for a specific event of the
-        // contract. In this case the `Transfer(address,address,uint256)` event.
-        .event(USDC::Transfer::SIGNATURE)
-        .from_block(BlockNumberOrTag::Latest);
-
-    // Initialize the poller and start watching
-    // `with_limit` (optional) is used to limit the number of times to poll, defaults to 3
-    // `with_poll_interval` (optional) is used to set the interval between polls, defaults to 7 seconds
-    let poller = provider.watch_logs(&filter).await.unwrap();
-    let timer_id = poller
-        .with_limit(Some(POLL_LIMIT))
-        .with_poll_interval(Duration::from_secs(10))
-        .start(callback)
-        .unwrap();
-
-    // Save timer id to be able to stop watch before completion
     STATE.with_borrow_mut(|state| {
-        state.timer_id = Some(timer_id);
+        if state.default_watch_id.is_none() {
+            let watch_id = state.next_watch_id;
+            state.next_watch_id += 1;
+            state.watches.insert(
+                watch_id,
+                WatchConfig {
+                    watch_rpc_service: get_rpc_service_base(),
+                    token_address: address!("833589fcd6edb6e08f4c7c32d4f71b54bda02913"),
+                    event_signature: Erc20::Transfer::SIGNATURE.to_string(),
+                    min_value: U256::from(1_000_000),
+                    nft_contract: address!("63A0bfd6a5cdCF446ae12135E2CD86b908659568"),
+                    mint_rpc_service: get_rpc_service_sepolia(),
+                    mint_chain_id: 11155111,
+                    last_processed_block: None,
+                },
+            );
+            state.default_watch_id = Some(watch_id);
+        }
+
+        // Clear the logs and poll count when starting a new watch
+        state.logs.clear();
+        state.poll_count = 0;
+        state.task_disabled.remove(&Task::UsdcTransferMonitor);
+        // Force the first tick to run the monitor immediately instead of
+        // waiting a full period.
+        state.task_last_run.remove(&Task::UsdcTransferMonitor);
     });
 
-    Ok(format!("Watching for logs, polling {} times.", POLL_LIMIT))
+    ensure_scheduler_running();
+
+    Ok(format!(
+        "Watching for logs every {:?}.",
+        Task::UsdcTransferMonitor.period()
+    ))
 }
 
 /// Stop the watch before it reaches completion
 #[ic_cdk::update]
 async fn watch_usdc_transfer_stop() -> Result<String, String> {
-    STATE.with_borrow_mut(|state| {
-        if let Some(timer_id) = state.timer_id.take() {
-            ic_cdk_timers::clear_timer(timer_id);
-            Ok(())
-        } else {
-            Err("No timer to clear.".to_string())
-        }
-    })?;
+    let was_enabled = STATE.with_borrow_mut(|state| {
+        state.task_disabled.insert(Task::UsdcTransferMonitor, true) != Some(true)
+    });
+    if !was_enabled {
+        return Err("No timer to clear.".to_string());
+    }
 
     Ok("Watching for logs stopped.".to_string())
 }
@@ -229,19 +816,266 @@ async fn watch_usdc_transfer_stop() -> Result<String, String> {
 /// Returns a boolean that is `true` when watching and `false` otherwise.
 #[ic_cdk::query]
 async fn watch_usdc_transfer_is_polling() -> Result<bool, String> {
-    STATE.with_borrow(|state| Ok(state.timer_id.is_some()))
+    STATE.with_borrow(|state| {
+        Ok(state.scheduler_timer_id.is_some()
+            && !state
+                .task_disabled
+                .get(&Task::UsdcTransferMonitor)
+                .copied()
+                .unwrap_or(false))
+    })
 }
 
-/// Returns the number of polls made. Polls finish automatically, once the `POLL_LIMIT`
-/// has been reached. This count is used to create a good interactive UI experience.
+/// Returns the number of polls made since the watch was last (re)started.
 #[ic_cdk::query]
 async fn watch_usdc_transfer_poll_count() -> Result<usize, String> {
     STATE.with_borrow(|state| Ok(state.poll_count))
 }
 
+/// Enables or disables an individual scheduler task without affecting the
+/// others, so e.g. nonce resync can keep running while the transfer monitor
+/// is paused.
+#[ic_cdk::update]
+fn scheduler_set_task_enabled(task: Task, enabled: bool) -> Result<(), String> {
+    STATE.with_borrow_mut(|state| {
+        if enabled {
+            state.task_disabled.remove(&task);
+        } else {
+            state.task_disabled.insert(task, true);
+        }
+    });
+    if enabled {
+        ensure_scheduler_running();
+    }
+    Ok(())
+}
+
+/// Returns, for every task, whether it is enabled and when it last ran.
+#[ic_cdk::query]
+fn scheduler_status() -> Vec<(Task, bool, Option<u64>)> {
+    STATE.with_borrow(|state| {
+        Task::ALL
+            .into_iter()
+            .map(|task| {
+                let enabled = !state.task_disabled.get(&task).copied().unwrap_or(false);
+                let last_run = state.task_last_run.get(&task).copied();
+                (task, enabled, last_run)
+            })
+            .collect()
+    })
+}
+
 /// Returns the list of logs returned by the watch. Gets reset on each start.
 #[ic_cdk::query]
 async fn watch_usdc_transfer_get() -> Result<Vec<String>, String> {
     STATE.with_borrow(|state| Ok(state.logs.iter().map(|log| format!("{log:?}")).collect()))
 }
 
+/// Returns the highest block whose logs a given watch has fully processed,
+/// `None` if the watch has never run or doesn't exist. Lets operators
+/// confirm a watch's sync cursor isn't stuck or skipping a range.
+#[ic_cdk::query]
+fn watch_cursor(watch_id: u64) -> Option<u64> {
+    STATE.with_borrow(|state| state.watches.get(&watch_id)?.last_processed_block)
+}
+
+/// Registers a new watch and starts tracking it from the current chain tip.
+/// Returns the id used to query or unregister it.
+///
+/// `watch_rpc_service`/`mint_rpc_service` and `mint_chain_id` are plain data
+/// rather than a fixed set of supported chains, so watching or minting on a
+/// chain this canister didn't ship with is just a matter of passing a
+/// different `RpcServices` value here, not a code change.
+#[ic_cdk::update]
+fn register_watch(
+    watch_rpc_service: RpcServices,
+    token_address: Address,
+    event_signature: String,
+    min_value: U256,
+    nft_contract: Address,
+    mint_rpc_service: RpcServices,
+    mint_chain_id: u64,
+) -> Result<u64, String> {
+    // The log decode step only understands the standard ERC-20
+    // `Transfer(address,address,uint256)` event shape, so every log matched
+    // by a watch for any other event would fail to decode and be skipped.
+    if event_signature != Erc20::Transfer::SIGNATURE {
+        return Err(format!(
+            "unsupported event signature {event_signature:?}; only {:?} is supported",
+            Erc20::Transfer::SIGNATURE
+        ));
+    }
+
+    Ok(STATE.with_borrow_mut(|state| {
+        let watch_id = state.next_watch_id;
+        state.next_watch_id += 1;
+        state.watches.insert(
+            watch_id,
+            WatchConfig {
+                watch_rpc_service,
+                token_address,
+                event_signature,
+                min_value,
+                nft_contract,
+                mint_rpc_service,
+                mint_chain_id,
+                last_processed_block: None,
+            },
+        );
+        watch_id
+    }))
+}
+
+/// Removes a registered watch so it stops being polled. Any mint jobs it has
+/// already queued are left to run to completion.
+#[ic_cdk::update]
+fn unregister_watch(watch_id: u64) -> Result<(), String> {
+    STATE.with_borrow_mut(|state| {
+        if state.watches.remove(&watch_id).is_none() {
+            return Err(format!("no watch registered with id {watch_id}"));
+        }
+        if state.default_watch_id == Some(watch_id) {
+            state.default_watch_id = None;
+        }
+        Ok(())
+    })
+}
+
+/// Returns every registered watch along with its id.
+#[ic_cdk::query]
+fn list_watches() -> Vec<(u64, WatchConfig)> {
+    STATE.with_borrow(|state| {
+        state
+            .watches
+            .iter()
+            .map(|(id, w)| (*id, w.clone()))
+            .collect()
+    })
+}
+
+/// Returns the mints still waiting to be sent or retried.
+#[ic_cdk::query]
+fn mint_queue_status() -> Vec<MintJob> {
+    STATE.with_borrow(|state| state.mint_queue.iter().cloned().collect())
+}
+
+/// Returns the mints that were dropped after exhausting `MINT_MAX_ATTEMPTS`.
+#[ic_cdk::query]
+fn mint_failures() -> Vec<String> {
+    STATE.with_borrow(|state| state.mint_failures.clone())
+}
+
+/// Sets how many blocks must pass after the block recorded when a mint was
+/// queued for an address before that address becomes eligible for another one.
+#[ic_cdk::update]
+fn set_whale_cooldown_blocks(blocks: u64) -> Result<(), String> {
+    STATE.with_borrow_mut(|state| state.whale_cooldown_blocks = blocks);
+    Ok(())
+}
+
+/// Returns every whale a mint has been queued for, with the block recorded
+/// when it was queued, so the frontend can show an "already rewarded" status.
+/// Entries are not removed once their cooldown has elapsed, so the list can
+/// include whales that are eligible again.
+#[ic_cdk::query]
+fn whale_waitlist_status() -> Vec<(Address, u64)> {
+    STATE.with_borrow(|state| state.whale_waitlist.iter().map(|(a, b)| (*a, *b)).collect())
+}
+
+/// Returns the cached nonce for `address` on `chain_id` and when it was last
+/// refreshed from the chain, so the frontend can detect a desynced nonce
+/// cache. The same address has an independent nonce sequence per chain.
+#[ic_cdk::query]
+fn nonce_status(address: Address, chain_id: u64) -> Result<NonceStatus, String> {
+    NONCE_CACHE.with_borrow(|cache| {
+        let key = (address, chain_id);
+        Ok(NonceStatus {
+            cached_nonce: cache.next_nonce.get(&key).copied(),
+            last_refreshed_at: cache.last_refreshed_at.get(&key).copied(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(byte: u8) -> Address {
+        Address::from([byte; 20])
+    }
+
+    #[test]
+    fn nonce_cache_has_nothing_cached_until_refreshed() {
+        let cache = NonceCache::default();
+        assert_eq!(cache.cached(addr(1), 1), None);
+    }
+
+    #[test]
+    fn nonce_cache_returns_the_refreshed_nonce() {
+        let mut cache = NonceCache::default();
+        cache.refresh(addr(1), 1, 5);
+        assert_eq!(cache.cached(addr(1), 1), Some(5));
+    }
+
+    #[test]
+    fn nonce_cache_keys_are_per_address_and_chain() {
+        let mut cache = NonceCache::default();
+        cache.refresh(addr(1), 1, 5);
+        assert_eq!(cache.cached(addr(2), 1), None);
+        assert_eq!(cache.cached(addr(1), 2), None);
+    }
+
+    #[test]
+    fn nonce_cache_advance_moves_past_the_used_nonce() {
+        let mut cache = NonceCache::default();
+        cache.refresh(addr(1), 1, 5);
+        cache.advance(addr(1), 1, 5);
+        assert_eq!(cache.cached(addr(1), 1), Some(6));
+    }
+
+    #[test]
+    fn nonce_cache_invalidate_hides_the_cached_nonce() {
+        let mut cache = NonceCache::default();
+        cache.refresh(addr(1), 1, 5);
+        cache.invalidate(addr(1), 1);
+        assert_eq!(cache.cached(addr(1), 1), None);
+    }
+
+    #[test]
+    fn nonce_cache_refresh_after_invalidate_is_trusted_again() {
+        let mut cache = NonceCache::default();
+        cache.refresh(addr(1), 1, 5);
+        cache.invalidate(addr(1), 1);
+        cache.refresh(addr(1), 1, 7);
+        assert_eq!(cache.cached(addr(1), 1), Some(7));
+    }
+
+    #[test]
+    fn mint_backoff_doubles_each_attempt() {
+        assert_eq!(mint_backoff_blocks(1), MINT_BACKOFF_BASE_BLOCKS);
+        assert_eq!(mint_backoff_blocks(2), MINT_BACKOFF_BASE_BLOCKS * 2);
+        assert_eq!(mint_backoff_blocks(3), MINT_BACKOFF_BASE_BLOCKS * 4);
+    }
+
+    #[test]
+    fn whale_is_on_cooldown_until_the_window_elapses() {
+        assert!(is_whale_on_cooldown(100, 50, 100));
+        assert!(!is_whale_on_cooldown(150, 50, 100));
+        assert!(!is_whale_on_cooldown(151, 50, 100));
+    }
+
+    #[test]
+    fn whale_cooldown_handles_equal_blocks() {
+        assert!(is_whale_on_cooldown(50, 50, 100));
+    }
+
+    #[test]
+    fn block_window_end_caps_at_the_window_size() {
+        assert_eq!(block_window_end(0, 10_000), BLOCK_WINDOW - 1);
+    }
+
+    #[test]
+    fn block_window_end_caps_at_latest_block_when_close_to_the_tip() {
+        assert_eq!(block_window_end(10, 20), 20);
+    }
+}